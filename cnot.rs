@@ -42,55 +42,159 @@ impl std::fmt::Display for RustEdition {
     }
 }
 
-macro_rules! unwrap_bool {
-    ($result:expr) => {
-        match $result {
-            Ok(value) => value,
-            Err(_) => return true,
-        }
-    };
+/// Path to the sidecar cache file that records the hash of the inputs used
+/// to produce `output_path`, next to the output itself.
+fn cache_path(output_path: &str) -> String {
+    format!("{output_path}.spar-cache")
 }
 
-fn needs_rebuild<T>(output_path: &str, sources: &[T]) -> bool
-where 
+/// Hashes the contents of every source file together with the edition and
+/// rustc flags used to build them, so that a change to any of them is
+/// detected even when file mtimes don't move (or move spuriously).
+fn build_hash<T>(sources: &[T], edition: &RustEdition, rustc_args: &[(&str, Option<&str>)]) -> u64
+where
     T: AsRef<str>,
 {
-    let output_meta = unwrap_bool!(std::fs::metadata(output_path));
+    use std::hash::{Hash, Hasher};
 
+    let mut hasher = std::collections::hash_map::DefaultHasher::new();
     for source in sources {
-        let s = source.as_ref();
-        let source_meta = unwrap_bool!(std::fs::metadata(std::path::Path::new(s)));
-        let output_time = unwrap_bool!(output_meta.modified());
-        let source_time = unwrap_bool!(source_meta.modified());
-        if output_time < source_time {
-            return true;
+        std::fs::read(source.as_ref()).unwrap_or_default().hash(&mut hasher);
+    }
+    edition.to_string().hash(&mut hasher);
+    rustc_args.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Returns `true` if `output_path` is missing, its cache file is missing,
+/// or the combined hash of `sources`/`edition`/`rustc_args` no longer
+/// matches the cached hash from the last successful build.
+fn needs_rebuild<T>(output_path: &str, sources: &[T], edition: &RustEdition, rustc_args: &[(&str, Option<&str>)]) -> bool
+where
+    T: AsRef<str>,
+{
+    if !std::path::Path::new(output_path).exists() {
+        return true;
+    }
+
+    let cached_hash = std::fs::read_to_string(cache_path(output_path))
+        .ok()
+        .and_then(|contents| contents.trim().parse::<u64>().ok());
+
+    cached_hash != Some(build_hash(sources, edition, rustc_args))
+}
+
+/// Records the combined hash of `sources`/`edition`/`rustc_args` in
+/// `output_path`'s cache file after a successful build.
+fn write_build_cache<T>(output_path: &str, sources: &[T], edition: &RustEdition, rustc_args: &[(&str, Option<&str>)])
+where
+    T: AsRef<str>,
+{
+    let hash = build_hash(sources, edition, rustc_args);
+    let _ = std::fs::write(cache_path(output_path), hash.to_string());
+}
+
+/// Error from invoking an external process: either it couldn't be spawned,
+/// or it ran to completion with a non-zero exit status.
+#[derive(Debug)]
+pub enum CommandError {
+    Spawn(std::io::Error),
+    ExitStatus(std::process::ExitStatus),
+}
+
+impl std::fmt::Display for CommandError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Spawn(err) => write!(f, "failed to spawn process: {err}"),
+            Self::ExitStatus(status) => write!(f, "process exited with {status}"),
         }
     }
-    false
 }
 
+impl std::error::Error for CommandError {}
+
+/// Runs `command` to completion, inheriting stdout/stderr and logging the
+/// invocation at [`LogLevel::INFO`]. Modeled on the `run`/`try_run_silent`
+/// helpers in rustc's own bootstrap tooling.
+fn run(command: &mut std::process::Command) -> Result<(), CommandError> {
+    log!(LogLevel::INFO, "running {command:?}");
+    let status = command.status().map_err(CommandError::Spawn)?;
+    if !status.success() {
+        return Err(CommandError::ExitStatus(status));
+    }
+    Ok(())
+}
+
+/// Like [`run`], but captures stdout/stderr instead of inheriting them and
+/// returns them regardless of exit status, so a caller can inspect a failed
+/// run's output (e.g. to parse diagnostics) instead of just learning that it
+/// failed.
+fn try_run_silent(command: &mut std::process::Command) -> Result<std::process::Output, CommandError> {
+    log!(LogLevel::INFO, "running {command:?}");
+    command.output().map_err(CommandError::Spawn)
+}
+
+/// Error from the `rebuild_*` family: the build (or the bookkeeping around
+/// it, such as creating the dependency cache directory or renaming the
+/// freshly built binary into place) failed. A failure to write the build
+/// cache is not reported.
+#[derive(Debug)]
+pub enum RebuildError {
+    Command(CommandError),
+    Io(std::io::Error),
+}
+
+impl From<CommandError> for RebuildError {
+    fn from(err: CommandError) -> Self {
+        Self::Command(err)
+    }
+}
+
+impl From<std::io::Error> for RebuildError {
+    fn from(err: std::io::Error) -> Self {
+        Self::Io(err)
+    }
+}
+
+impl std::fmt::Display for RebuildError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Command(err) => write!(f, "{err}"),
+            Self::Io(err) => write!(f, "{err}"),
+        }
+    }
+}
+
+impl std::error::Error for RebuildError {}
+
 /// Rebuilds the program with predefined edition (R2024) and O3 optimizations.
 ///
 /// First arg in `proc_args` must be the path to the executable.
 ///
-/// First source file is considered the main file
-pub fn rebuild<T>(proc_args: &mut dyn Iterator<Item = String>, sources: &[T])
+/// First source file is considered the main file.
+///
+/// A successful rebuild re-execs the freshly built binary and exits the
+/// process, so this only returns `Ok(())` when `proc_args` is empty or no
+/// rebuild was necessary; it returns `Err` if the build (or re-exec) failed.
+pub fn rebuild<T>(proc_args: &mut dyn Iterator<Item = String>, sources: &[T]) -> Result<(), RebuildError>
 where
     T: AsRef<str>,
 {
-    rebuild_edition(proc_args, RustEdition::R2024, sources);
+    rebuild_edition(proc_args, RustEdition::R2024, sources)
 }
 
 /// Rebuilds the program with O3 optimizations and a custom edition.
 ///
 /// First arg in `proc_args` must be the path to the executable.
 ///
-/// First source file is considered the main file
+/// First source file is considered the main file. See [`rebuild`] for the
+/// meaning of the return value.
 pub fn rebuild_edition<T>(
     proc_args: &mut dyn Iterator<Item = String>,
     edition: RustEdition,
     sources: &[T],
-) where
+) -> Result<(), RebuildError>
+where
     T: AsRef<str>,
 {
     rebuild_edition_args(
@@ -98,99 +202,813 @@ pub fn rebuild_edition<T>(
         edition,
         sources,
         &[("-O", None)],
-    );
+    )
 }
 
 /// Rebuilds the program with no additional flags and a custom edition.
 ///
 /// First arg in `proc_args` must be the path to the executable.
 ///
-/// First source file is considered the main file
+/// First source file is considered the main file. See [`rebuild`] for the
+/// meaning of the return value.
 pub fn rebuild_edition_args<T>(
     proc_args: &mut dyn Iterator<Item = String>,
     edition: RustEdition,
     sources: &[T],
     rustc_args: &[(&str, Option<&str>)],
-) where
+) -> Result<(), RebuildError>
+where
     T: AsRef<str>,
 {
-    let self_path = match proc_args.next() {
-        Some(self_path) => self_path,
-        None => return,
-    };
-    if !needs_rebuild(&self_path, &sources) {
-        return;
+    rebuild_edition_args_deps(proc_args, edition, sources, rustc_args, &[])
+}
+
+/// Directory where source dependencies are compiled to `.rlib`s, keyed by
+/// crate name, so repeated runs reuse them instead of recompiling every time.
+const DEP_CACHE_DIR: &str = ".spar-deps";
+
+/// A single external dependency: a crate name paired with the path to either
+/// its source file or a prebuilt `.rlib`.
+pub struct Dependency {
+    pub name: String,
+    pub path: String,
+}
+
+/// Parses `//! dep: name = "path"` directives out of a source file's
+/// contents, e.g. `//! dep: rand = "./vendor/rand/lib.rs"`.
+pub fn parse_dependencies(source: &str) -> Vec<Dependency> {
+    source
+        .lines()
+        .filter_map(|line| line.trim().strip_prefix("//! dep:"))
+        .filter_map(|rest| {
+            let (name, path) = rest.split_once('=')?;
+            Some(Dependency {
+                name: name.trim().to_string(),
+                path: path.trim().trim_matches('"').to_string(),
+            })
+        })
+        .collect()
+}
+
+/// Merges `extra` into `parsed`, cloning each so callers can extend a
+/// dependency list parsed from a source file with their own explicit list
+/// without the two aliasing.
+fn merge_dependencies(mut parsed: Vec<Dependency>, extra: &[Dependency]) -> Vec<Dependency> {
+    parsed.extend(extra.iter().map(|dep| Dependency {
+        name: dep.name.clone(),
+        path: dep.path.clone(),
+    }));
+    parsed
+}
+
+/// Path to `name`'s cached `.rlib`, keyed by `target` (mirrors
+/// [`target_output_path`] for the main binary) so a host build and a
+/// cross-compiled build of the same dependency don't clobber each other's
+/// cache entry.
+fn dependency_rlib_path(name: &str, target: Option<&str>) -> String {
+    match target {
+        Some(target) => format!("{DEP_CACHE_DIR}/lib{name}-{target}.rlib"),
+        None => format!("{DEP_CACHE_DIR}/lib{name}.rlib"),
+    }
+}
+
+/// Compiles `dep` to a cached `.rlib` if it is a source file and out of
+/// date, returning the path to the `.rlib` to pass to `--extern`. A
+/// dependency that already points at an `.rlib` is used as-is. `target`,
+/// when given, is forwarded to rustc as `--target` and folded into the
+/// cached `.rlib`'s path so it isn't linked into a binary built for a
+/// different target than it was compiled for.
+fn build_dependency(dep: &Dependency, edition: &RustEdition, target: Option<&str>) -> Result<String, RebuildError> {
+    if dep.path.ends_with(".rlib") {
+        return Ok(dep.path.clone());
     }
 
+    std::fs::create_dir_all(DEP_CACHE_DIR)?;
+    let rlib_path = dependency_rlib_path(&dep.name, target);
+    let dep_sources = [&dep.path];
+    let dep_rustc_args: &[(&str, Option<&str>)] = &[];
+    if !needs_rebuild(&rlib_path, &dep_sources, edition, dep_rustc_args) {
+        return Ok(rlib_path);
+    }
+
+    log!(LogLevel::INFO, "Building dependency `{}`", dep.name);
+    let mut args = vec!["--edition".to_string(), edition.to_string(), "--crate-type".to_string(), "lib".to_string()];
+    if let Some(target) = target {
+        args.push("--target".to_string());
+        args.push(target.to_string());
+    }
+    args.push("-o".to_string());
+    args.push(rlib_path.clone());
+    args.push(dep.path.clone());
+    run(std::process::Command::new("rustc").args(args))?;
+    write_build_cache(&rlib_path, &dep_sources, edition, dep_rustc_args);
+    Ok(rlib_path)
+}
+
+/// Dependencies declared in `sources[0]`'s `//! dep:` directives merged with
+/// `extra_deps`, in the same precedence [`resolve_compile_args`] uses when
+/// building rustc's args. Exposed separately so a caller can also fold the
+/// resolved paths into a rebuild's hashed inputs, since a `//! dep:` file
+/// isn't otherwise among `sources`.
+fn resolve_dependencies<T>(sources: &[T], extra_deps: &[Dependency]) -> Vec<Dependency>
+where
+    T: AsRef<str>,
+{
+    let main_source = std::fs::read_to_string(sources[0].as_ref()).unwrap_or_default();
+    merge_dependencies(parse_dependencies(&main_source), extra_deps)
+}
+
+/// Builds the rustc argument list for `rustc_args` plus `deps` (as resolved
+/// by [`resolve_dependencies`]): `-L dependency=<DEP_CACHE_DIR>` followed by
+/// an `--extern name=path` per dependency, compiling each source dependency
+/// to a cached `.rlib` along the way. `target`, when given, is forwarded to
+/// [`build_dependency`] so cross-compiled builds link against `.rlib`s built
+/// for that target instead of the host. Shared by every `rebuild_*` variant
+/// that accepts dependencies.
+fn resolve_compile_args(
+    edition: &RustEdition,
+    rustc_args: &[(&str, Option<&str>)],
+    deps: &[Dependency],
+    target: Option<&str>,
+) -> Result<Vec<String>, RebuildError> {
     let mut args = vec![];
     for (arg, value) in rustc_args {
-        args.push(arg);
+        args.push(arg.to_string());
         if let Some(value) = value {
-            args.push(value);
+            args.push(value.to_string());
         }
     }
 
-    let status = std::process::Command::new("rustc")
-        .args(args)
-        .args([
-            "--edition",
-            &edition.to_string(),
-            "-o",
-            &self_path,
-            sources[0].as_ref()
-        ])
-        .status()
-        .expect("failed to rebuild");
+    if !deps.is_empty() {
+        args.push("-L".to_string());
+        args.push(format!("dependency={DEP_CACHE_DIR}"));
+    }
+    for dep in deps {
+        let rlib_path = build_dependency(dep, edition, target)?;
+        args.push("--extern".to_string());
+        args.push(format!("{}={rlib_path}", dep.name));
+    }
+
+    Ok(args)
+}
 
-    if !status.success() {
-        log!(LogLevel::ERROR, "Build failed");
-        std::process::exit(1);
+/// `sources` followed by the path of every resolved dependency, so a change
+/// to a `//! dep:`/`extra_deps` file is detected by [`needs_rebuild`] just
+/// like a change to `sources` itself, instead of only being noticed once
+/// [`build_dependency`]'s own (separately cached) staleness check runs.
+fn hash_inputs<T>(sources: &[T], deps: &[Dependency]) -> Vec<String>
+where
+    T: AsRef<str>,
+{
+    sources
+        .iter()
+        .map(|source| source.as_ref().to_string())
+        .chain(deps.iter().map(|dep| dep.path.clone()))
+        .collect()
+}
+
+/// A minimal JSON value, just enough to pick fields out of rustc's
+/// `--error-format=json` diagnostic output without a JSON dependency.
+enum Json {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    fn get(&self, key: &str) -> Option<&Json> {
+        match self {
+            Json::Object(fields) => fields.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    fn as_str(&self) -> Option<&str> {
+        match self {
+            Json::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    fn as_array(&self) -> Option<&[Json]> {
+        match self {
+            Json::Array(values) => Some(values),
+            _ => None,
+        }
+    }
+
+    fn as_u32(&self) -> Option<u32> {
+        match self {
+            Json::Number(n) => Some(*n as u32),
+            _ => None,
+        }
+    }
+}
+
+struct JsonParser<'a> {
+    chars: std::iter::Peekable<std::str::Chars<'a>>,
+}
+
+impl<'a> JsonParser<'a> {
+    fn new(input: &'a str) -> Self {
+        Self { chars: input.chars().peekable() }
+    }
+
+    fn skip_whitespace(&mut self) {
+        while matches!(self.chars.peek(), Some(c) if c.is_whitespace()) {
+            self.chars.next();
+        }
+    }
+
+    fn parse_value(&mut self) -> Option<Json> {
+        self.skip_whitespace();
+        match self.chars.peek()? {
+            '{' => self.parse_object(),
+            '[' => self.parse_array(),
+            '"' => self.parse_string().map(Json::String),
+            't' | 'f' => self.parse_bool(),
+            'n' => self.parse_null(),
+            _ => self.parse_number(),
+        }
+    }
+
+    fn parse_object(&mut self) -> Option<Json> {
+        self.chars.next(); // '{'
+        let mut fields = vec![];
+        self.skip_whitespace();
+        if self.chars.peek() == Some(&'}') {
+            self.chars.next();
+            return Some(Json::Object(fields));
+        }
+        loop {
+            self.skip_whitespace();
+            let key = self.parse_string()?;
+            self.skip_whitespace();
+            if self.chars.next()? != ':' {
+                return None;
+            }
+            let value = self.parse_value()?;
+            fields.push((key, value));
+            self.skip_whitespace();
+            match self.chars.next()? {
+                ',' => continue,
+                '}' => break,
+                _ => return None,
+            }
+        }
+        Some(Json::Object(fields))
+    }
+
+    fn parse_array(&mut self) -> Option<Json> {
+        self.chars.next(); // '['
+        let mut values = vec![];
+        self.skip_whitespace();
+        if self.chars.peek() == Some(&']') {
+            self.chars.next();
+            return Some(Json::Array(values));
+        }
+        loop {
+            values.push(self.parse_value()?);
+            self.skip_whitespace();
+            match self.chars.next()? {
+                ',' => continue,
+                ']' => break,
+                _ => return None,
+            }
+        }
+        Some(Json::Array(values))
     }
 
+    fn parse_string(&mut self) -> Option<String> {
+        self.skip_whitespace();
+        if self.chars.next()? != '"' {
+            return None;
+        }
+        let mut s = String::new();
+        loop {
+            match self.chars.next()? {
+                '"' => break,
+                '\\' => match self.chars.next()? {
+                    'n' => s.push('\n'),
+                    't' => s.push('\t'),
+                    'r' => s.push('\r'),
+                    '"' => s.push('"'),
+                    '\\' => s.push('\\'),
+                    '/' => s.push('/'),
+                    'u' => {
+                        let code: String = (0..4).filter_map(|_| self.chars.next()).collect();
+                        let code = u32::from_str_radix(&code, 16).ok()?;
+                        s.push(char::from_u32(code).unwrap_or('\u{fffd}'));
+                    }
+                    other => s.push(other),
+                },
+                c => s.push(c),
+            }
+        }
+        Some(s)
+    }
+
+    fn expect_literal(&mut self, literal: &str) -> bool {
+        literal.chars().all(|expected| self.chars.next() == Some(expected))
+    }
+
+    fn parse_bool(&mut self) -> Option<Json> {
+        if self.chars.peek() == Some(&'t') {
+            self.expect_literal("true").then_some(Json::Bool(true))
+        } else {
+            self.expect_literal("false").then_some(Json::Bool(false))
+        }
+    }
+
+    fn parse_null(&mut self) -> Option<Json> {
+        self.expect_literal("null").then_some(Json::Null)
+    }
+
+    fn parse_number(&mut self) -> Option<Json> {
+        let mut s = String::new();
+        while matches!(self.chars.peek(), Some(c) if c.is_ascii_digit() || matches!(c, '-' | '+' | '.' | 'e' | 'E')) {
+            s.push(self.chars.next()?);
+        }
+        s.parse::<f64>().ok().map(Json::Number)
+    }
+}
+
+/// A source location referenced by a [`Diagnostic`].
+pub struct DiagnosticSpan {
+    pub file_name: String,
+    pub line_start: u32,
+    pub column_start: u32,
+}
+
+/// A single message parsed out of rustc's `--error-format=json` diagnostic
+/// output: its severity, text, any source spans it points at, and the
+/// human-readable rendering rustc would otherwise print directly.
+pub struct Diagnostic {
+    pub level: String,
+    pub message: String,
+    pub spans: Vec<DiagnosticSpan>,
+    pub rendered: Option<String>,
+}
+
+/// Parses the newline-delimited JSON objects rustc emits on stderr with
+/// `--error-format=json` into [`Diagnostic`]s, skipping any line that isn't
+/// a well-formed diagnostic object.
+fn parse_diagnostics(stderr: &[u8]) -> Vec<Diagnostic> {
+    let stderr = String::from_utf8_lossy(stderr);
+    stderr
+        .lines()
+        .filter_map(|line| JsonParser::new(line).parse_value())
+        .filter_map(|value| {
+            let level = value.get("level")?.as_str()?.to_string();
+            let message = value.get("message")?.as_str()?.to_string();
+            let spans = value
+                .get("spans")
+                .and_then(Json::as_array)
+                .map(|spans| {
+                    spans
+                        .iter()
+                        .filter_map(|span| {
+                            Some(DiagnosticSpan {
+                                file_name: span.get("file_name")?.as_str()?.to_string(),
+                                line_start: span.get("line_start")?.as_u32()?,
+                                column_start: span.get("column_start")?.as_u32()?,
+                            })
+                        })
+                        .collect()
+                })
+                .unwrap_or_default();
+            let rendered = value.get("rendered").and_then(Json::as_str).map(str::to_string);
+            Some(Diagnostic { level, message, spans, rendered })
+        })
+        .collect()
+}
+
+/// Temporary path next to `self_path` that the `rebuild_*` functions compile
+/// `sources[0]` into and then rename over `self_path`, rather than writing
+/// `self_path` directly. On Windows, a running executable's file can't be overwritten
+/// in place; renaming a freshly built binary over it works on every
+/// platform, since the old process only ever holds the old file by name.
+fn temp_output_path(self_path: &str) -> String {
+    format!("{self_path}.new")
+}
+
+/// Rebuilds the program with custom rustc flags (`rustc_args`), a custom
+/// edition, and explicit external dependencies, in addition to any `//! dep:` directives
+/// found in the main source file.
+///
+/// First arg in `proc_args` must be the path to the executable.
+///
+/// First source file is considered the main file. See [`rebuild`] for the
+/// meaning of the return value.
+pub fn rebuild_edition_args_deps<T>(
+    proc_args: &mut dyn Iterator<Item = String>,
+    edition: RustEdition,
+    sources: &[T],
+    rustc_args: &[(&str, Option<&str>)],
+    extra_deps: &[Dependency],
+) -> Result<(), RebuildError>
+where
+    T: AsRef<str>,
+{
+    let self_path = match proc_args.next() {
+        Some(self_path) => self_path,
+        None => return Ok(()),
+    };
+    let deps = resolve_dependencies(sources, extra_deps);
+    let hash_inputs = hash_inputs(sources, &deps);
+    if !needs_rebuild(&self_path, &hash_inputs, &edition, rustc_args) {
+        return Ok(());
+    }
+
+    let args = resolve_compile_args(&edition, rustc_args, &deps, None)?;
+
+    let tmp_path = temp_output_path(&self_path);
+    run(std::process::Command::new("rustc").args(args).args([
+        "--edition",
+        &edition.to_string(),
+        "-o",
+        &tmp_path,
+        sources[0].as_ref(),
+    ]))?;
+    std::fs::rename(&tmp_path, &self_path)?;
+    write_build_cache(&self_path, &hash_inputs, &edition, rustc_args);
+
+    log!(LogLevel::INFO, "Build successful");
+    let mut child = std::process::Command::new(&self_path).args(proc_args).spawn()?;
+    child.wait()?;
+    std::process::exit(0);
+}
+
+/// Per-target output path for `self_path`, so host and cross-compiled
+/// artifacts for the same program don't clobber each other.
+fn target_output_path(self_path: &str, target: &str) -> String {
+    format!("{self_path}-{target}")
+}
+
+/// The host's own target triple, as reported by `rustc -vV`'s `host:` line.
+fn host_triple() -> Result<String, RebuildError> {
+    let output = try_run_silent(std::process::Command::new("rustc").arg("-vV"))?;
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    stdout
+        .lines()
+        .find_map(|line| line.strip_prefix("host: "))
+        .map(str::to_string)
+        .ok_or_else(|| RebuildError::Io(std::io::Error::other("failed to determine host triple from `rustc -vV`")))
+}
+
+/// Rebuilds the program for `target` with predefined edition (R2024) and
+/// O3 optimizations. See [`rebuild_edition_args_deps_target`] for what
+/// `target` does and the meaning of the return value.
+pub fn rebuild_target<T>(
+    proc_args: &mut dyn Iterator<Item = String>,
+    sources: &[T],
+    target: &str,
+) -> Result<(), RebuildError>
+where
+    T: AsRef<str>,
+{
+    rebuild_edition_target(proc_args, RustEdition::R2024, sources, target)
+}
+
+/// Rebuilds the program for `target` with O3 optimizations and a custom
+/// edition. See [`rebuild_edition_args_deps_target`] for what `target` does
+/// and the meaning of the return value.
+pub fn rebuild_edition_target<T>(
+    proc_args: &mut dyn Iterator<Item = String>,
+    edition: RustEdition,
+    sources: &[T],
+    target: &str,
+) -> Result<(), RebuildError>
+where
+    T: AsRef<str>,
+{
+    rebuild_edition_args_target(proc_args, edition, sources, &[("-O", None)], target)
+}
+
+/// Rebuilds the program for `target` with custom rustc flags (`rustc_args`)
+/// and a custom edition. See [`rebuild_edition_args_deps_target`] for what `target` does
+/// and the meaning of the return value.
+pub fn rebuild_edition_args_target<T>(
+    proc_args: &mut dyn Iterator<Item = String>,
+    edition: RustEdition,
+    sources: &[T],
+    rustc_args: &[(&str, Option<&str>)],
+    target: &str,
+) -> Result<(), RebuildError>
+where
+    T: AsRef<str>,
+{
+    rebuild_edition_args_deps_target(proc_args, edition, sources, rustc_args, &[], target)
+}
+
+/// Rebuilds the program for `target` (a Rust target triple, e.g.
+/// `x86_64-pc-windows-gnu`), forwarded to rustc as `--target`.
+///
+/// When `target` matches the host's own triple, the output is written to
+/// `self_path` itself (so the re-exec'd binary keeps the same `self_path`
+/// it was invoked with, instead of accumulating a new `-<target>` suffix
+/// on every rebuild), and this re-execs the freshly built binary and exits,
+/// exactly like [`rebuild_edition_args_deps`]. Otherwise the produced
+/// binary can't run on the host, so it's written to a per-target path next
+/// to `self_path` (so host and cross artifacts coexist) and this returns
+/// `Ok(())` after a successful cross-build instead of trying to run it.
+pub fn rebuild_edition_args_deps_target<T>(
+    proc_args: &mut dyn Iterator<Item = String>,
+    edition: RustEdition,
+    sources: &[T],
+    rustc_args: &[(&str, Option<&str>)],
+    extra_deps: &[Dependency],
+    target: &str,
+) -> Result<(), RebuildError>
+where
+    T: AsRef<str>,
+{
+    let self_path = match proc_args.next() {
+        Some(self_path) => self_path,
+        None => return Ok(()),
+    };
+    let is_host_target = target == host_triple()?;
+    let output_path = if is_host_target {
+        self_path.clone()
+    } else {
+        target_output_path(&self_path, target)
+    };
+    let deps = resolve_dependencies(sources, extra_deps);
+    let hash_inputs = hash_inputs(sources, &deps);
+    if !needs_rebuild(&output_path, &hash_inputs, &edition, rustc_args) {
+        return Ok(());
+    }
+
+    let args = resolve_compile_args(&edition, rustc_args, &deps, Some(target))?;
+
+    let tmp_path = temp_output_path(&output_path);
+    run(std::process::Command::new("rustc").args(args).args([
+        "--edition",
+        &edition.to_string(),
+        "--target",
+        target,
+        "-o",
+        &tmp_path,
+        sources[0].as_ref(),
+    ]))?;
+    std::fs::rename(&tmp_path, &output_path)?;
+    write_build_cache(&output_path, &hash_inputs, &edition, rustc_args);
     log!(LogLevel::INFO, "Build successful");
-    std::process::Command::new(&self_path)
-        .args(proc_args)
-        .spawn()
-        .expect("program failed to run")
-        .wait()
-        .expect("program did not run");
+
+    if !is_host_target {
+        log!(LogLevel::INFO, "Built `{target}` binary at {output_path}; not runnable on host, skipping re-exec");
+        return Ok(());
+    }
+
+    let mut child = std::process::Command::new(&output_path).args(proc_args).spawn()?;
+    child.wait()?;
+    std::process::exit(0);
+}
+
+/// Like [`rebuild_edition_args_deps`], but builds with
+/// `--error-format=json` and returns the parsed [`Diagnostic`]s instead of
+/// failing outright on a failed build, so a standalone program can react to
+/// its own compile errors programmatically.
+///
+/// On a successful build this still re-execs and exits like the other
+/// `rebuild_*` functions; it only returns `Ok` when `proc_args` is empty or
+/// no rebuild was necessary (with no diagnostics), or when the build failed
+/// to compile (with that build's diagnostics). It returns `Err` if a
+/// dependency failed to build, rustc itself couldn't be spawned, the built
+/// binary couldn't be renamed into place, or re-exec failed.
+pub fn rebuild_edition_args_diagnostics<T>(
+    proc_args: &mut dyn Iterator<Item = String>,
+    edition: RustEdition,
+    sources: &[T],
+    rustc_args: &[(&str, Option<&str>)],
+    extra_deps: &[Dependency],
+) -> Result<Vec<Diagnostic>, RebuildError>
+where
+    T: AsRef<str>,
+{
+    let self_path = match proc_args.next() {
+        Some(self_path) => self_path,
+        None => return Ok(vec![]),
+    };
+    let deps = resolve_dependencies(sources, extra_deps);
+    let hash_inputs = hash_inputs(sources, &deps);
+    if !needs_rebuild(&self_path, &hash_inputs, &edition, rustc_args) {
+        return Ok(vec![]);
+    }
+
+    let args = resolve_compile_args(&edition, rustc_args, &deps, None)?;
+
+    let tmp_path = temp_output_path(&self_path);
+    let output = try_run_silent(std::process::Command::new("rustc").args(args).args([
+        "--edition",
+        &edition.to_string(),
+        "--error-format=json",
+        "-o",
+        &tmp_path,
+        sources[0].as_ref(),
+    ]))?;
+
+    let diagnostics = parse_diagnostics(&output.stderr);
+    for diagnostic in &diagnostics {
+        if let Some(rendered) = &diagnostic.rendered {
+            let level = if diagnostic.level == "error" { LogLevel::ERROR } else { LogLevel::INFO };
+            log!(level, "{rendered}");
+        }
+    }
+    let errors = diagnostics.iter().filter(|d| d.level == "error").count();
+    let warnings = diagnostics.iter().filter(|d| d.level == "warning").count();
+
+    if !output.status.success() {
+        log!(LogLevel::ERROR, "Build failed: {errors} errors, {warnings} warnings");
+        return Ok(diagnostics);
+    }
+    std::fs::rename(&tmp_path, &self_path)?;
+    write_build_cache(&self_path, &hash_inputs, &edition, rustc_args);
+
+    log!(LogLevel::INFO, "Build successful: {errors} errors, {warnings} warnings");
+    let mut child = std::process::Command::new(&self_path).args(proc_args).spawn()?;
+    child.wait()?;
     std::process::exit(0);
 }
 
+/// A standard-library crate known to rust-analyzer's sysroot stitching, along
+/// with the names of the other sysroot crates it depends on.
+struct SysrootCrate {
+    name: &'static str,
+    deps: &'static [&'static str],
+}
+
+/// Mirrors the subset of the sysroot crate graph rust-analyzer builds
+/// internally, in dependency order (a crate only ever names earlier crates
+/// as deps).
+const SYSROOT_CRATES: &[SysrootCrate] = &[
+    SysrootCrate { name: "core", deps: &[] },
+    SysrootCrate { name: "alloc", deps: &["core"] },
+    SysrootCrate { name: "panic_unwind", deps: &["core", "alloc"] },
+    SysrootCrate { name: "std", deps: &["core", "alloc", "panic_unwind"] },
+    SysrootCrate { name: "proc_macro", deps: &["core", "alloc", "std"] },
+    SysrootCrate { name: "test", deps: &["core", "alloc", "std"] },
+];
+
+/// An explicit sysroot to use instead of auto-discovering one via
+/// `rustc --print sysroot`.
+pub struct Sysroot {
+    /// Path to the sysroot root, as printed by `rustc --print sysroot`.
+    pub root: String,
+    /// Path to the sysroot's `library` source directory. Derived from
+    /// `root` as `<root>/lib/rustlib/src/rust/library` when not given.
+    pub src_root: Option<String>,
+}
+
+/// Environment variable that, when set, overrides sysroot auto-discovery
+/// with `SPAR_SYSROOT=<root>` (see [`Sysroot::root`]).
+const SPAR_SYSROOT_ENV: &str = "SPAR_SYSROOT";
+
+/// Resolves the sysroot root and `library` source directory, preferring an
+/// explicit `sysroot` override, then the `SPAR_SYSROOT` environment
+/// variable, and finally shelling out to `rustc --print sysroot`.
+fn resolve_sysroot(sysroot: Option<Sysroot>) -> std::io::Result<(String, String)> {
+    let sysroot = sysroot.or_else(|| {
+        std::env::var(SPAR_SYSROOT_ENV)
+            .ok()
+            .map(|root| Sysroot { root, src_root: None })
+    });
+
+    let sysroot = match sysroot {
+        Some(sysroot) => sysroot,
+        None => {
+            let output = try_run_silent(std::process::Command::new("rustc").args(["--print", "sysroot"]))
+                .map_err(|err| std::io::Error::other(err.to_string()))?;
+            if !output.status.success() {
+                return Err(std::io::Error::other("failed to get sysroot path"));
+            }
+            let stdout = String::from_utf8(output.stdout).map_err(std::io::Error::other)?;
+            let root = stdout
+                .lines()
+                .next()
+                .ok_or_else(|| std::io::Error::other("failed to get sysroot path"))?
+                .to_string();
+            Sysroot { root, src_root: None }
+        }
+    };
+
+    let src_root = sysroot
+        .src_root
+        .unwrap_or_else(|| format!("{}/lib/rustlib/src/rust/library", sysroot.root));
+    Ok((sysroot.root, src_root))
+}
+
 /// Generates `rust-project.json` to fix rust-analyzer not working on standalone files.
 pub fn generate_project(root_file: &str, edition: RustEdition) -> std::io::Result<()> {
+    generate_project_deps(root_file, edition, &[])
+}
+
+/// Generates `rust-project.json`, additionally wiring up `deps` (external
+/// dependencies) as their own crates so rust-analyzer can resolve them too,
+/// merged with any `//! dep:` directives found in `root_file` itself — the
+/// same two sources [`resolve_dependencies`] merges at build time.
+pub fn generate_project_deps(root_file: &str, edition: RustEdition, deps: &[Dependency]) -> std::io::Result<()> {
+    generate_project_sysroot(root_file, edition, deps, None)
+}
+
+/// Generates `rust-project.json`, additionally accepting an explicit
+/// `sysroot` override (see [`Sysroot`]) instead of always shelling out to
+/// `rustc --print sysroot`.
+pub fn generate_project_sysroot(
+    root_file: &str,
+    edition: RustEdition,
+    deps: &[Dependency],
+    sysroot: Option<Sysroot>,
+) -> std::io::Result<()> {
     if std::fs::exists("rust-project.json")? {
         return Ok(());
     }
 
-    let sysroot_path = std::process::Command::new("rustc")
-        .args(["--print", "sysroot"])
-        .output()
-        .expect("failed to get sysroot");
-    if !sysroot_path.status.success() {
-        eprintln!("Failed to get sysroot path");
-        return Ok(());
+    let (_sysroot_root, src_root) = resolve_sysroot(sysroot)?;
+
+    let root_source = std::fs::read_to_string(root_file).unwrap_or_default();
+    let deps = merge_dependencies(parse_dependencies(&root_source), deps);
+
+    let mut crates = String::new();
+    for sysroot_crate in SYSROOT_CRATES {
+        let deps = sysroot_crate
+            .deps
+            .iter()
+            .map(|dep| format!(r#"{{ "crate": {dep_index}, "name": "{dep}" }}"#, dep_index = sysroot_crate_index(dep)))
+            .collect::<Vec<_>>()
+            .join(",\n            ");
+        crates.push_str(&format!(
+            r#"    {{
+        "root_module": "{src_root}/{name}/src/lib.rs",
+        "edition": "{edition}",
+        "deps": [
+            {deps}
+        ]
+    }},
+"#,
+            name = sysroot_crate.name,
+        ));
+    }
+
+    // rust-analyzer's `root_module` must point at a source file, so a
+    // dependency that only ships a prebuilt `.rlib` can't get a crate entry
+    // of its own; it's still passed to rustc via `--extern`, it just isn't
+    // resolvable by rust-analyzer.
+    let source_deps: Vec<&Dependency> = deps.iter().filter(|dep| !dep.path.ends_with(".rlib")).collect();
+
+    let std_index = sysroot_crate_index("std");
+    for dep in &source_deps {
+        crates.push_str(&format!(
+            r#"    {{
+        "root_module": "{path}",
+        "edition": "{edition}",
+        "deps": [
+            {{ "crate": {std_index}, "name": "std" }}
+        ]
+    }},
+"#,
+            path = dep.path,
+        ));
     }
-    let sysroot_path = String::from_utf8(sysroot_path.stdout).unwrap();
-    let mut sysroot_path = sysroot_path.lines();
+
+    let mut root_deps = ["core", "alloc", "std"]
+        .iter()
+        .map(|dep| format!(r#"{{ "crate": {}, "name": "{dep}" }}"#, sysroot_crate_index(dep)))
+        .collect::<Vec<_>>();
+    for (i, dep) in source_deps.iter().enumerate() {
+        let dep_index = SYSROOT_CRATES.len() + i;
+        root_deps.push(format!(r#"{{ "crate": {dep_index}, "name": "{}" }}"#, dep.name));
+    }
+    let root_deps = root_deps.join(",\n            ");
 
     std::fs::write(
         "rust-project.json",
         &format!(
             r#"{{
-"sysroot_src": "{}/lib/rustlib/src/rust/library",
+"sysroot_src": "{src_root}",
 "crates": [
-    {{
-        "root_module": "{}",
-        "edition": "{}",
-        "deps": []
+{crates}    {{
+        "root_module": "{root_file}",
+        "edition": "{edition}",
+        "deps": [
+            {root_deps}
+        ]
     }}
 ]
 }}"#,
-            sysroot_path.next().ok_or_else(|| std::io::Error::other("failed to get sysroot path"))?,
-            root_file,
-            edition
         ),
     )?;
     Ok(())
 }
+
+/// Index of `name` within [`SYSROOT_CRATES`], used to build the `"crate"`
+/// references in the generated crate graph.
+fn sysroot_crate_index(name: &str) -> usize {
+    SYSROOT_CRATES
+        .iter()
+        .position(|sysroot_crate| sysroot_crate.name == name)
+        .expect("unknown sysroot crate")
+}